@@ -0,0 +1,186 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A generic visitor/rewriter over stackless `Bytecode`, mirroring the expression-rewriter
+//! pattern already used for `Exp` trees. Every processor in this pipeline used to iterate
+//! `self.data.code` by hand and reconstruct instructions; implementing a handful of hooks here
+//! instead lets a substitution or temp-renaming pass be expressed declaratively, and keeps the
+//! rewrite in lock-step with the proxy maps on `FunctionData` via `FunctionDataBuilder::rewrite_bytecode`.
+
+use crate::{
+    function_target::FunctionDataBuilder,
+    stackless_bytecode::{AssignKind, Bytecode, Label, Operation, TempIndex},
+};
+
+/// A trait for rewriting `Bytecode` instructions. Every method has a default implementation
+/// that leaves its part of the instruction unchanged, so an implementor overrides only the
+/// hooks relevant to its transformation. Overriding `rewrite_temp` alone, for instance, is
+/// enough to implement a variable renaming, since every other hook routes temps through it.
+pub trait BytecodeRewriter {
+    /// Rewrites a temporary index. Called for every temp appearing anywhere in an instruction:
+    /// call sources and destinations, branch conditions, and return values.
+    fn rewrite_temp(&mut self, temp: TempIndex) -> TempIndex {
+        temp
+    }
+
+    /// Rewrites an `Assign` instruction's parts.
+    fn rewrite_assign(
+        &mut self,
+        dest: TempIndex,
+        src: TempIndex,
+        kind: AssignKind,
+    ) -> (TempIndex, TempIndex, AssignKind) {
+        (self.rewrite_temp(dest), self.rewrite_temp(src), kind)
+    }
+
+    /// Rewrites a `Call` instruction's parts.
+    fn rewrite_call(
+        &mut self,
+        dests: Vec<TempIndex>,
+        op: Operation,
+        srcs: Vec<TempIndex>,
+    ) -> (Vec<TempIndex>, Operation, Vec<TempIndex>) {
+        (
+            dests.into_iter().map(|t| self.rewrite_temp(t)).collect(),
+            self.rewrite_operation(op),
+            srcs.into_iter().map(|t| self.rewrite_temp(t)).collect(),
+        )
+    }
+
+    /// Rewrites the `Operation` of a `Call`. Most rewriters leave this unchanged; override this
+    /// to e.g. retarget a call to a different function.
+    fn rewrite_operation(&mut self, op: Operation) -> Operation {
+        op
+    }
+
+    /// Rewrites a conditional `Branch`'s target labels and condition temp.
+    fn rewrite_branch(
+        &mut self,
+        then_label: Label,
+        else_label: Label,
+        cond: TempIndex,
+    ) -> (Label, Label, TempIndex) {
+        (then_label, else_label, self.rewrite_temp(cond))
+    }
+
+    /// Rewrites the temps returned by a `Ret` instruction.
+    fn rewrite_ret(&mut self, temps: Vec<TempIndex>) -> Vec<TempIndex> {
+        temps.into_iter().map(|t| self.rewrite_temp(t)).collect()
+    }
+}
+
+/// Walks `code`, dispatching each instruction to the relevant `BytecodeRewriter` hook and
+/// rebuilding it from the (possibly changed) parts. Every other instruction (`Label`, `Jump`,
+/// `Nop`, ...) passes through unchanged, labels included; only a `Branch`'s labels go through a
+/// hook.
+pub fn rewrite_code<R: BytecodeRewriter>(code: &[Bytecode], rewriter: &mut R) -> Vec<Bytecode> {
+    code.iter()
+        .cloned()
+        .map(|instr| rewrite_instr(instr, rewriter))
+        .collect()
+}
+
+fn rewrite_instr<R: BytecodeRewriter>(instr: Bytecode, rewriter: &mut R) -> Bytecode {
+    match instr {
+        Bytecode::Assign(attr, dest, src, kind) => {
+            let (dest, src, kind) = rewriter.rewrite_assign(dest, src, kind);
+            Bytecode::Assign(attr, dest, src, kind)
+        }
+        Bytecode::Call(attr, dests, op, srcs) => {
+            let (dests, op, srcs) = rewriter.rewrite_call(dests, op, srcs);
+            Bytecode::Call(attr, dests, op, srcs)
+        }
+        Bytecode::Ret(attr, temps) => Bytecode::Ret(attr, rewriter.rewrite_ret(temps)),
+        Bytecode::Load(attr, dest, cons) => {
+            Bytecode::Load(attr, rewriter.rewrite_temp(dest), cons)
+        }
+        Bytecode::Branch(attr, then_label, else_label, cond) => {
+            let (then_label, else_label, cond) =
+                rewriter.rewrite_branch(then_label, else_label, cond);
+            Bytecode::Branch(attr, then_label, else_label, cond)
+        }
+        Bytecode::Abort(attr, temp) => Bytecode::Abort(attr, rewriter.rewrite_temp(temp)),
+        other => other,
+    }
+}
+
+impl<'a> FunctionDataBuilder<'a> {
+    /// Rewrites this function's entire body with `rewriter`, then applies the same temp
+    /// remapping to `param_proxy_map` and `ref_param_proxy_map` so the maps and the code never
+    /// drift apart. Processors should use this instead of hand-rolling a match-and-rebuild loop
+    /// over `self.data.code`.
+    ///
+    /// `ref_param_return_map`'s values are return-slot positions, not temps (see
+    /// `get_return_index`/`get_input_for_return_index`), so only its keys -- which are temps --
+    /// are remapped; piping a return-slot position through `rewrite_temp` would silently
+    /// corrupt it.
+    pub fn rewrite_bytecode<R: BytecodeRewriter>(&mut self, rewriter: &mut R) {
+        self.data.code = rewrite_code(&self.data.code, &mut *rewriter);
+        let remap = |map: &mut std::collections::BTreeMap<usize, usize>, rewriter: &mut R| {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(x, y)| (rewriter.rewrite_temp(x), rewriter.rewrite_temp(y)))
+                .collect();
+        };
+        remap(&mut self.data.param_proxy_map, rewriter);
+        remap(&mut self.data.ref_param_proxy_map, rewriter);
+        self.data.ref_param_return_map = std::mem::take(&mut self.data.ref_param_return_map)
+            .into_iter()
+            .map(|(x, y)| (rewriter.rewrite_temp(x), y))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_target::FunctionData;
+    use std::collections::BTreeMap;
+
+    /// Shifts every temp up by one, a stand-in for the kind of non-identity renaming a
+    /// processor performs after inserting a new local.
+    struct ShiftByOne;
+
+    impl BytecodeRewriter for ShiftByOne {
+        fn rewrite_temp(&mut self, temp: TempIndex) -> TempIndex {
+            temp + 1
+        }
+    }
+
+    fn empty_function_data() -> FunctionData {
+        FunctionData {
+            code: vec![],
+            local_types: vec![],
+            return_types: vec![],
+            param_proxy_map: BTreeMap::new(),
+            ref_param_proxy_map: BTreeMap::new(),
+            ref_param_return_map: BTreeMap::new(),
+            acquires_global_resources: vec![],
+            locations: BTreeMap::new(),
+            annotations: Default::default(),
+            spec_blocks_on_impl: BTreeMap::new(),
+            name_to_index: BTreeMap::new(),
+            modify_targets: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn rewrite_bytecode_remaps_proxy_maps_but_not_return_positions() {
+        let mut data = empty_function_data();
+        data.param_proxy_map.insert(0, 1);
+        data.ref_param_proxy_map.insert(2, 3);
+        // Key 4 is the ref parameter's temp; value 0 is a return-slot position, not a temp.
+        data.ref_param_return_map.insert(4, 0);
+
+        let mut builder = FunctionDataBuilder {
+            data: &mut data,
+            next_attr_index: 0,
+        };
+        builder.rewrite_bytecode(&mut ShiftByOne);
+
+        assert_eq!(data.param_proxy_map.get(&1), Some(&2));
+        assert_eq!(data.ref_param_proxy_map.get(&3), Some(&4));
+        // The key (a temp) moved, but the return-slot position must be untouched.
+        assert_eq!(data.ref_param_return_map.get(&5), Some(&0));
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A whole-program call graph over a `FunctionTargetsHolder`, built by unioning the per-function
+//! callee sets `FunctionData::get_callees` already extracts. On top of the graph, callers can
+//! prune unreachable functions from a chosen root set, and can identify mutually recursive
+//! function groups via strongly connected components -- both verification ordering and
+//! termination/opaqueness handling need to know which functions call each other, directly or
+//! transitively.
+
+use move_model::model::{FunId, QualifiedId};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::function_target_pipeline::FunctionTargetsHolder;
+
+type Fun = QualifiedId<FunId>;
+
+/// A whole-program call graph: for each function with a target, the set of functions it calls
+/// directly.
+#[derive(Debug)]
+pub struct CallGraph {
+    edges: BTreeMap<Fun, BTreeSet<Fun>>,
+}
+
+impl CallGraph {
+    /// Builds the call graph by unioning `FunctionData::get_callees` across every function
+    /// tracked by `targets`.
+    pub fn new(targets: &FunctionTargetsHolder) -> Self {
+        let mut edges = BTreeMap::new();
+        for fun in targets.get_funs() {
+            let callees = targets
+                .get_data(&fun)
+                .map(|data| data.get_callees())
+                .unwrap_or_default();
+            edges.insert(fun, callees);
+        }
+        CallGraph { edges }
+    }
+
+    /// Returns the functions called directly by `fun`.
+    pub fn callees(&self, fun: &Fun) -> &BTreeSet<Fun> {
+        static EMPTY: BTreeSet<Fun> = BTreeSet::new();
+        self.edges.get(fun).unwrap_or(&EMPTY)
+    }
+
+    /// Returns every function transitively reachable from `roots` (inclusive of the roots
+    /// themselves), following call edges. Processing can skip or prune anything not in this
+    /// set, since it can never be entered from a root.
+    pub fn reachable_from(&self, roots: impl IntoIterator<Item = Fun>) -> BTreeSet<Fun> {
+        let mut seen = BTreeSet::new();
+        let mut frontier: Vec<Fun> = roots.into_iter().collect();
+        while let Some(fun) = frontier.pop() {
+            if seen.insert(fun) {
+                frontier.extend(self.callees(&fun).iter().cloned());
+            }
+        }
+        seen
+    }
+
+    /// Computes the strongly connected components of this graph via Tarjan's algorithm.
+    pub fn strongly_connected_components(&self) -> StronglyConnectedComponents {
+        StronglyConnectedComponents::compute(self)
+    }
+}
+
+/// The strongly connected components of a `CallGraph`, identifying mutually recursive groups of
+/// functions.
+#[derive(Debug)]
+pub struct StronglyConnectedComponents {
+    scc_of: BTreeMap<Fun, usize>,
+    sccs: Vec<Vec<Fun>>,
+}
+
+impl StronglyConnectedComponents {
+    /// Runs Tarjan's algorithm with an explicit work stack (instead of recursion, which would
+    /// overflow on the large, flat call graphs this crate can see) to find the strongly
+    /// connected components of `graph`.
+    fn compute(graph: &CallGraph) -> Self {
+        let mut index_counter = 0usize;
+        let mut index: BTreeMap<Fun, usize> = BTreeMap::new();
+        let mut lowlink: BTreeMap<Fun, usize> = BTreeMap::new();
+        let mut on_stack: BTreeSet<Fun> = BTreeSet::new();
+        let mut node_stack: Vec<Fun> = vec![];
+        let mut sccs: Vec<Vec<Fun>> = vec![];
+
+        for &start in graph.edges.keys() {
+            if index.contains_key(&start) {
+                continue;
+            }
+            // Work stack entries are (node, index into its successor list to visit next).
+            let mut work: Vec<(Fun, usize)> = vec![(start, 0)];
+            index.insert(start, index_counter);
+            lowlink.insert(start, index_counter);
+            index_counter += 1;
+            node_stack.push(start);
+            on_stack.insert(start);
+
+            while let Some(&(node, succ_pos)) = work.last() {
+                let successors: Vec<Fun> = graph.callees(&node).iter().cloned().collect();
+                if succ_pos < successors.len() {
+                    work.last_mut().unwrap().1 += 1;
+                    let succ = successors[succ_pos];
+                    if !index.contains_key(&succ) {
+                        index.insert(succ, index_counter);
+                        lowlink.insert(succ, index_counter);
+                        index_counter += 1;
+                        node_stack.push(succ);
+                        on_stack.insert(succ);
+                        work.push((succ, 0));
+                    } else if on_stack.contains(&succ) {
+                        let succ_index = index[&succ];
+                        let node_low = lowlink.get_mut(&node).unwrap();
+                        *node_low = (*node_low).min(succ_index);
+                    }
+                } else {
+                    work.pop();
+                    if let Some(&(parent, _)) = work.last() {
+                        let node_low = lowlink[&node];
+                        let parent_low = lowlink.get_mut(&parent).unwrap();
+                        *parent_low = (*parent_low).min(node_low);
+                    }
+                    if lowlink[&node] == index[&node] {
+                        let mut scc = vec![];
+                        loop {
+                            let w = node_stack.pop().unwrap();
+                            on_stack.remove(&w);
+                            scc.push(w);
+                            if w == node {
+                                break;
+                            }
+                        }
+                        sccs.push(scc);
+                    }
+                }
+            }
+        }
+
+        let mut scc_of = BTreeMap::new();
+        for (i, scc) in sccs.iter().enumerate() {
+            for &fun in scc {
+                scc_of.insert(fun, i);
+            }
+        }
+        StronglyConnectedComponents { scc_of, sccs }
+    }
+
+    /// Returns true if `fun` is part of a mutually recursive group: either its component has
+    /// more than one function, or it calls itself directly.
+    pub fn is_recursive(&self, fun: &Fun, graph: &CallGraph) -> bool {
+        match self.scc_of.get(fun) {
+            Some(&i) => self.sccs[i].len() > 1 || graph.callees(fun).contains(fun),
+            None => false,
+        }
+    }
+
+    /// Returns all functions in `fun`'s strongly connected component, including `fun` itself.
+    pub fn scc_of(&self, fun: &Fun) -> &[Fun] {
+        match self.scc_of.get(fun) {
+            Some(&i) => &self.sccs[i],
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use move_model::model::ModuleId;
+
+    fn fun(idx: u16) -> Fun {
+        ModuleId::new(idx).qualified(FunId::new(idx))
+    }
+
+    fn graph_from_edges(edges: Vec<(Fun, Vec<Fun>)>) -> CallGraph {
+        CallGraph {
+            edges: edges
+                .into_iter()
+                .map(|(f, callees)| (f, callees.into_iter().collect()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reachable_from_follows_transitive_calls_but_not_unreachable_ones() {
+        let (f0, f1, f2, f3) = (fun(0), fun(1), fun(2), fun(3));
+        // f0 -> f1 -> f2; f3 calls nothing and is called by nobody.
+        let graph = graph_from_edges(vec![
+            (f0, vec![f1]),
+            (f1, vec![f2]),
+            (f2, vec![]),
+            (f3, vec![]),
+        ]);
+
+        let reachable = graph.reachable_from(vec![f0]);
+        assert!(reachable.contains(&f0));
+        assert!(reachable.contains(&f1));
+        assert!(reachable.contains(&f2));
+        assert!(!reachable.contains(&f3));
+    }
+
+    #[test]
+    fn scc_detects_mutual_recursion_but_not_a_linear_chain() {
+        let (f0, f1, f2, f3) = (fun(0), fun(1), fun(2), fun(3));
+        // f0 -> f1 <-> f2 (mutually recursive), f2 -> f3 (not part of the cycle).
+        let graph = graph_from_edges(vec![
+            (f0, vec![f1]),
+            (f1, vec![f2]),
+            (f2, vec![f1, f3]),
+            (f3, vec![]),
+        ]);
+
+        let sccs = graph.strongly_connected_components();
+        assert!(sccs.is_recursive(&f1, &graph));
+        assert!(sccs.is_recursive(&f2, &graph));
+        assert!(!sccs.is_recursive(&f0, &graph));
+        assert!(!sccs.is_recursive(&f3, &graph));
+
+        let f1_scc: BTreeSet<_> = sccs.scc_of(&f1).iter().cloned().collect();
+        assert_eq!(f1_scc, vec![f1, f2].into_iter().collect());
+    }
+
+    #[test]
+    fn scc_detects_direct_self_recursion() {
+        let f0 = fun(0);
+        let graph = graph_from_edges(vec![(f0, vec![f0])]);
+        let sccs = graph.strongly_connected_components();
+        assert!(sccs.is_recursive(&f0, &graph));
+    }
+}
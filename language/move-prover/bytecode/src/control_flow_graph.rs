@@ -0,0 +1,469 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Provides a control flow graph over the stackless bytecode of a function, plus a dominator
+//! tree computed on top of it. Other analyses (`borrow_analysis`, `livevar_analysis`, and
+//! eventually SSA/loop-invariant inference) can consume this structural view instead of each
+//! re-deriving basic blocks and dominance from `FunctionData::code` by hand.
+
+use crate::stackless_bytecode::Bytecode;
+use std::collections::{BTreeMap, BTreeSet};
+use vm::file_format::CodeOffset;
+
+/// Identifies a basic block in a `ControlFlowGraph`. Block ids are dense indices into the
+/// graph's internal block list, not code offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockId(usize);
+
+/// A basic block: a maximal run of instructions with a single entry point (its first
+/// instruction) and a single exit point (its last instruction).
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    /// Code offset of the first instruction in the block, inclusive.
+    pub lower: CodeOffset,
+    /// Code offset of the last instruction in the block, inclusive.
+    pub upper: CodeOffset,
+    successors: Vec<BlockId>,
+    predecessors: Vec<BlockId>,
+}
+
+/// A control flow graph over the bytecode of a single function, partitioned into basic blocks
+/// at labels and after branches, jumps, returns, and aborts.
+#[derive(Debug)]
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+    entry: BlockId,
+    /// Synthetic block with no instructions, with an incoming edge from every block ending in
+    /// `Ret` or `Abort`. Gives analyses that want a single exit point somewhere to attach to,
+    /// without requiring every function to end in exactly one such instruction.
+    exit: BlockId,
+}
+
+impl ControlFlowGraph {
+    /// Builds the control flow graph for `code`.
+    pub fn new(code: &[Bytecode]) -> Self {
+        if code.is_empty() {
+            // Native functions (and anything else with no bytecode) have nothing to partition
+            // into blocks; model them as a single block that is both the entry and the exit,
+            // the same way `next_free_attr_index`/`next_free_label_index` on `FunctionData`
+            // treat empty code as the base case rather than an error.
+            let block = BasicBlock {
+                lower: 0,
+                upper: 0,
+                successors: vec![],
+                predecessors: vec![],
+            };
+            return ControlFlowGraph {
+                blocks: vec![block],
+                entry: BlockId(0),
+                exit: BlockId(0),
+            };
+        }
+
+        let label_offsets: BTreeMap<_, _> = code
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, instr)| {
+                if let Bytecode::Label(_, label) = instr {
+                    Some((*label, offset as CodeOffset))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut leaders: BTreeSet<CodeOffset> = BTreeSet::new();
+        leaders.insert(0);
+        for (offset, instr) in code.iter().enumerate() {
+            let offset = offset as CodeOffset;
+            match instr {
+                Bytecode::Label(..) => {
+                    leaders.insert(offset);
+                }
+                Bytecode::Jump(_, label) => {
+                    leaders.insert(label_offsets[label]);
+                    if offset + 1 < code.len() as CodeOffset {
+                        leaders.insert(offset + 1);
+                    }
+                }
+                Bytecode::Branch(_, then_label, else_label, _) => {
+                    leaders.insert(label_offsets[then_label]);
+                    leaders.insert(label_offsets[else_label]);
+                    if offset + 1 < code.len() as CodeOffset {
+                        leaders.insert(offset + 1);
+                    }
+                }
+                Bytecode::Ret(..) | Bytecode::Abort(..) => {
+                    if offset + 1 < code.len() as CodeOffset {
+                        leaders.insert(offset + 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let leader_list: Vec<CodeOffset> = leaders.into_iter().collect();
+        let mut blocks = Vec::with_capacity(leader_list.len() + 1);
+        let mut block_of_offset = BTreeMap::new();
+        for (i, &lower) in leader_list.iter().enumerate() {
+            let upper = leader_list
+                .get(i + 1)
+                .map(|&next| next - 1)
+                .unwrap_or(code.len() as CodeOffset - 1);
+            block_of_offset.insert(lower, BlockId(blocks.len()));
+            blocks.push(BasicBlock {
+                lower,
+                upper,
+                successors: vec![],
+                predecessors: vec![],
+            });
+        }
+        let exit = BlockId(blocks.len());
+        blocks.push(BasicBlock {
+            lower: code.len() as CodeOffset,
+            upper: code.len() as CodeOffset,
+            successors: vec![],
+            predecessors: vec![],
+        });
+
+        let mut cfg = ControlFlowGraph {
+            blocks,
+            entry: BlockId(0),
+            exit,
+        };
+        cfg.link_successors(code, &label_offsets, &block_of_offset);
+        cfg
+    }
+
+    fn link_successors(
+        &mut self,
+        code: &[Bytecode],
+        label_offsets: &BTreeMap<crate::stackless_bytecode::Label, CodeOffset>,
+        block_of_offset: &BTreeMap<CodeOffset, BlockId>,
+    ) {
+        let block_count = self.blocks.len() - 1; // exclude the synthetic exit block
+        let edges: Vec<(BlockId, Vec<BlockId>)> = (0..block_count)
+            .map(|i| {
+                let id = BlockId(i);
+                let upper = self.blocks[i].upper;
+                let targets = match &code[upper as usize] {
+                    Bytecode::Jump(_, label) => vec![block_of_offset[&label_offsets[label]]],
+                    Bytecode::Branch(_, then_label, else_label, _) => vec![
+                        block_of_offset[&label_offsets[then_label]],
+                        block_of_offset[&label_offsets[else_label]],
+                    ],
+                    Bytecode::Ret(..) | Bytecode::Abort(..) => vec![self.exit],
+                    _ => {
+                        if i + 1 < block_count {
+                            vec![BlockId(i + 1)]
+                        } else {
+                            vec![self.exit]
+                        }
+                    }
+                };
+                (id, targets)
+            })
+            .collect();
+        for (id, targets) in edges {
+            for target in targets {
+                self.blocks[id.0].successors.push(target);
+                self.blocks[target.0].predecessors.push(id);
+            }
+        }
+    }
+
+    /// Returns the entry block of this function.
+    pub fn entry_block(&self) -> BlockId {
+        self.entry
+    }
+
+    /// Returns the synthetic exit block, reached from every `Ret` and `Abort`.
+    pub fn exit_block(&self) -> BlockId {
+        self.exit
+    }
+
+    /// Returns all block ids, including the synthetic exit block.
+    pub fn blocks(&self) -> impl Iterator<Item = BlockId> {
+        (0..self.blocks.len()).map(BlockId)
+    }
+
+    /// Returns the code offset range `[lower, upper]` of a basic block. The synthetic exit block
+    /// holds no instructions; its range is `[code.len(), code.len()]`, just past the end of the
+    /// code.
+    pub fn block_range(&self, block: BlockId) -> (CodeOffset, CodeOffset) {
+        let b = &self.blocks[block.0];
+        (b.lower, b.upper)
+    }
+
+    /// Returns the successor blocks of `block`.
+    pub fn successors(&self, block: BlockId) -> &[BlockId] {
+        &self.blocks[block.0].successors
+    }
+
+    /// Returns the predecessor blocks of `block`.
+    pub fn predecessors(&self, block: BlockId) -> &[BlockId] {
+        &self.blocks[block.0].predecessors
+    }
+
+    /// Computes the dominator tree of this graph.
+    pub fn dominator_tree(&self) -> DominatorTree {
+        DominatorTree::compute(self)
+    }
+}
+
+/// A dominator tree computed over a `ControlFlowGraph`, using the iterative Cooper-Harvey-
+/// Kennedy algorithm (Cooper, Harvey, Kennedy, "A Simple, Fast Dominance Algorithm", 2001). It
+/// converges in a small, bounded number of passes and avoids the union-find bookkeeping of
+/// Lengauer-Tarjan, which matters little at the size of the bodies this crate generates but
+/// keeps the implementation simple to audit.
+#[derive(Debug)]
+pub struct DominatorTree {
+    /// Maps a reachable block to its position in postorder (the order in which a depth-first
+    /// search from the entry finishes visiting it). The entry block has the highest number.
+    postorder_number: BTreeMap<BlockId, usize>,
+    /// The block occupying each postorder position; inverse of `postorder_number`.
+    block_at_postorder: Vec<BlockId>,
+    /// `idom[p]` is the postorder position of the immediate dominator of the block at postorder
+    /// position `p`.
+    idom: Vec<usize>,
+}
+
+impl DominatorTree {
+    fn compute(cfg: &ControlFlowGraph) -> Self {
+        let postorder = Self::postorder(cfg);
+        let mut postorder_number = BTreeMap::new();
+        for (i, &b) in postorder.iter().enumerate() {
+            postorder_number.insert(b, i);
+        }
+        let entry_number = postorder_number[&cfg.entry];
+
+        // Reverse postorder, skipping the entry, is the order in which the fixpoint below
+        // processes blocks.
+        let rpo: Vec<BlockId> = postorder.iter().rev().cloned().collect();
+
+        let mut idom = vec![usize::MAX; postorder.len()];
+        idom[entry_number] = entry_number;
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in rpo.iter().skip(1) {
+                let b_number = postorder_number[&b];
+                let mut processed_preds = cfg
+                    .predecessors(b)
+                    .iter()
+                    .filter(|p| {
+                        postorder_number
+                            .get(p)
+                            .map_or(false, |&n| idom[n] != usize::MAX)
+                    });
+                let new_idom = match processed_preds.next() {
+                    Some(&first) => {
+                        let mut new_idom = postorder_number[&first];
+                        for &p in processed_preds {
+                            new_idom = Self::intersect(&idom, postorder_number[&p], new_idom);
+                        }
+                        new_idom
+                    }
+                    // None of b's predecessors have a dominator yet (the path through the loop
+                    // header that reaches b hasn't been processed this pass); pick it up on the
+                    // next pass once that predecessor is resolved.
+                    None => continue,
+                };
+                if idom[b_number] != new_idom {
+                    idom[b_number] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        DominatorTree {
+            postorder_number,
+            block_at_postorder: postorder,
+            idom,
+        }
+    }
+
+    fn postorder(cfg: &ControlFlowGraph) -> Vec<BlockId> {
+        let mut order = Vec::new();
+        let mut visited = BTreeSet::new();
+        visited.insert(cfg.entry);
+        // Explicit stack of (block, next successor index to visit), avoiding recursion on the
+        // large generated bodies this crate produces.
+        let mut stack: Vec<(BlockId, usize)> = vec![(cfg.entry, 0)];
+        while let Some(&(block, next)) = stack.last() {
+            let successors = cfg.successors(block);
+            if next < successors.len() {
+                let succ = successors[next];
+                stack.last_mut().unwrap().1 += 1;
+                if visited.insert(succ) {
+                    stack.push((succ, 0));
+                }
+            } else {
+                order.push(block);
+                stack.pop();
+            }
+        }
+        order
+    }
+
+    fn intersect(idom: &[usize], mut finger_a: usize, mut finger_b: usize) -> usize {
+        while finger_a != finger_b {
+            while finger_a < finger_b {
+                finger_a = idom[finger_a];
+            }
+            while finger_b < finger_a {
+                finger_b = idom[finger_b];
+            }
+        }
+        finger_a
+    }
+
+    /// Returns the immediate dominator of `block`, or `None` if `block` is the entry or is
+    /// unreachable.
+    pub fn immediate_dominator(&self, block: BlockId) -> Option<BlockId> {
+        let number = *self.postorder_number.get(&block)?;
+        let idom_number = self.idom[number];
+        if idom_number == number {
+            None
+        } else {
+            Some(self.block_at_postorder[idom_number])
+        }
+    }
+
+    /// Returns true if `a` dominates `b` (every path from the entry to `b` passes through `a`).
+    /// A block dominates itself.
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        let (Some(&a_number), Some(&b_number)) = (
+            self.postorder_number.get(&a),
+            self.postorder_number.get(&b),
+        ) else {
+            return false;
+        };
+        let mut current = b_number;
+        loop {
+            if current == a_number {
+                return true;
+            }
+            let parent = self.idom[current];
+            if parent == current {
+                return false;
+            }
+            current = parent;
+        }
+    }
+
+    /// Computes the dominance frontier of every reachable block: for a block `b`, the set of
+    /// blocks that `b` does not strictly dominate but that have a predecessor dominated by `b`.
+    /// This is the standard input to SSA construction and loop-invariant placement.
+    pub fn dominance_frontiers(&self, cfg: &ControlFlowGraph) -> BTreeMap<BlockId, BTreeSet<BlockId>> {
+        let mut frontiers: BTreeMap<BlockId, BTreeSet<BlockId>> = BTreeMap::new();
+        for block in cfg.blocks() {
+            if !self.postorder_number.contains_key(&block) {
+                continue;
+            }
+            if cfg.predecessors(block).len() < 2 {
+                continue;
+            }
+            let idom = match self.immediate_dominator(block) {
+                Some(idom) => idom,
+                None => continue,
+            };
+            for &pred in cfg.predecessors(block) {
+                if !self.postorder_number.contains_key(&pred) {
+                    continue;
+                }
+                let mut runner = pred;
+                while runner != idom {
+                    frontiers.entry(runner).or_default().insert(block);
+                    runner = match self.immediate_dominator(runner) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_code_yields_a_single_entry_exit_block_without_panicking() {
+        let cfg = ControlFlowGraph::new(&[]);
+        assert_eq!(cfg.entry_block(), cfg.exit_block());
+        assert_eq!(cfg.blocks().count(), 1);
+    }
+
+    /// Builds a `ControlFlowGraph` directly from a successor adjacency list, bypassing
+    /// `Bytecode` entirely, so the dominator algorithm can be tested against a hand-picked
+    /// shape instead of indirectly through a fabricated instruction stream. Block `n - 1` is
+    /// treated as the exit.
+    fn graph_from_successors(successors: Vec<Vec<usize>>) -> ControlFlowGraph {
+        let n = successors.len();
+        let mut blocks: Vec<BasicBlock> = successors
+            .into_iter()
+            .enumerate()
+            .map(|(i, succs)| BasicBlock {
+                lower: i as CodeOffset,
+                upper: i as CodeOffset,
+                successors: succs.into_iter().map(BlockId).collect(),
+                predecessors: vec![],
+            })
+            .collect();
+        for i in 0..n {
+            let succs = blocks[i].successors.clone();
+            for succ in succs {
+                blocks[succ.0].predecessors.push(BlockId(i));
+            }
+        }
+        ControlFlowGraph {
+            blocks,
+            entry: BlockId(0),
+            exit: BlockId(n - 1),
+        }
+    }
+
+    #[test]
+    fn dominator_tree_on_a_diamond_merges_at_the_join_block() {
+        // 0 -> {1, 2}, 1 -> 3, 2 -> 3: a diamond converging on block 3.
+        let cfg = graph_from_successors(vec![vec![1, 2], vec![3], vec![3], vec![]]);
+        let doms = cfg.dominator_tree();
+
+        assert_eq!(doms.immediate_dominator(BlockId(0)), None);
+        assert_eq!(doms.immediate_dominator(BlockId(1)), Some(BlockId(0)));
+        assert_eq!(doms.immediate_dominator(BlockId(2)), Some(BlockId(0)));
+        // Neither arm of the diamond dominates the join; it merges straight back to the entry.
+        assert_eq!(doms.immediate_dominator(BlockId(3)), Some(BlockId(0)));
+
+        assert!(doms.dominates(BlockId(0), BlockId(3)));
+        assert!(!doms.dominates(BlockId(1), BlockId(3)));
+        assert!(!doms.dominates(BlockId(2), BlockId(3)));
+
+        let frontiers = doms.dominance_frontiers(&cfg);
+        assert_eq!(
+            frontiers.get(&BlockId(1)),
+            Some(&[BlockId(3)].into_iter().collect())
+        );
+        assert_eq!(
+            frontiers.get(&BlockId(2)),
+            Some(&[BlockId(3)].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn dominator_tree_handles_a_loop_back_edge() {
+        // 0 -> 1 -> 2, 2 -> 1 (back edge), 2 -> 3: a single-block loop body.
+        let cfg = graph_from_successors(vec![vec![1], vec![2], vec![1, 3], vec![]]);
+        let doms = cfg.dominator_tree();
+
+        assert_eq!(doms.immediate_dominator(BlockId(1)), Some(BlockId(0)));
+        assert_eq!(doms.immediate_dominator(BlockId(2)), Some(BlockId(1)));
+        assert_eq!(doms.immediate_dominator(BlockId(3)), Some(BlockId(2)));
+        assert!(doms.dominates(BlockId(1), BlockId(2)));
+        assert!(doms.dominates(BlockId(0), BlockId(3)));
+    }
+}
@@ -0,0 +1,476 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A binary serialization layer for `FunctionData`, keyed by a content hash of the source
+//! bytecode and the pipeline configuration that produced it. Re-deriving `FunctionData`
+//! (bytecode, local/return types, proxy maps, acquired resources, spec-block offsets) through
+//! the full transformation pipeline is expensive on large crates, yet deterministic for
+//! unchanged inputs, so a cache hit can reload it instead of rebuilding it.
+//!
+//! `Clone for FunctionData` already treats `annotations` as transient and drops them; this cache
+//! follows the same rule and additionally treats `name_to_index` and `modify_targets` as
+//! derived, since `FunctionData::new` already recomputes both from a `FunctionEnv` alone. Only
+//! the remaining, genuinely stable fields are persisted. On a cache hit, the pipeline reloads
+//! those fields and then re-runs only the annotation-producing analyses.
+//!
+//! `encode`/`decode` handle a single function; `encode_holder`/`decode_holder` do the same for
+//! an entire `FunctionTargetsHolder`, nesting one tagged container (the function's id and its
+//! `encode`d bytes) per entry inside an outer tagged container. `FunctionDataDiskCache` is the
+//! on-disk front end: it stores these containers as files named by `CacheKey`, and fails soft
+//! into a cache miss (`None`) rather than an error on a missing or corrupt file, since the
+//! caller's fallback is simply to recompute the target.
+
+use crate::{
+    function_target::FunctionData,
+    function_target_pipeline::FunctionTargetsHolder,
+    stackless_bytecode::{AttrId, Bytecode, SpecBlockId},
+};
+use move_model::{
+    model::{FunId, FunctionEnv, GlobalEnv, Loc, ModuleId, QualifiedId, StructId},
+    ty::Type,
+};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    convert::TryInto,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+use vm::file_format::CodeOffset;
+
+/// A cache key derived from a content hash of a function's original bytecode and a
+/// discriminator for the pipeline configuration (e.g. which processors ran, and in which
+/// order). Two runs with the same key are expected to produce the same persisted fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CacheKey(u64);
+
+impl CacheKey {
+    pub fn new(source_digest: &[u8], pipeline_digest: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        source_digest.hash(&mut hasher);
+        pipeline_digest.hash(&mut hasher);
+        CacheKey(hasher.finish())
+    }
+
+    /// A stable hex representation, suitable as a cache file name.
+    pub fn to_hex(self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+// =================================================================================================
+// Tagged container
+//
+// A self-describing binary format: a sequence of `(tag: u8, len: u32 LE, payload)` records.
+// Unlike a plain `bcs`-encoded struct, an unknown tag (written by a newer or older version of
+// this cache) is simply skipped by its recorded length, so adding or retiring a field never
+// breaks decoding of data written by a different pipeline version.
+
+const TAG_CODE: u8 = 1;
+const TAG_LOCAL_TYPES: u8 = 2;
+const TAG_RETURN_TYPES: u8 = 3;
+const TAG_PARAM_PROXY_MAP: u8 = 4;
+const TAG_REF_PARAM_PROXY_MAP: u8 = 5;
+const TAG_REF_PARAM_RETURN_MAP: u8 = 6;
+const TAG_ACQUIRES_GLOBAL_RESOURCES: u8 = 7;
+const TAG_LOCATIONS: u8 = 8;
+const TAG_SPEC_BLOCKS_ON_IMPL: u8 = 9;
+
+// One `TAG_HOLDER_ENTRY` record per function in a `FunctionTargetsHolder`, its payload itself a
+// nested tagged container of an entry's own two fields.
+const TAG_HOLDER_ENTRY: u8 = 10;
+const TAG_ENTRY_FUN_ID: u8 = 1;
+const TAG_ENTRY_FUNCTION_DATA: u8 = 2;
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Writer { buf: Vec::new() }
+    }
+
+    fn field(&mut self, tag: u8, payload: &[u8]) {
+        self.buf.push(tag);
+        self.buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(payload);
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Iterates the `(tag, payload)` records of a tagged container, skipping unknown tags by their
+/// recorded length rather than failing to parse them.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    /// Reads the next `(tag, payload)` record. Returns `Ok(None)` at a clean end of the buffer,
+    /// and `Err` (rather than panicking on an out-of-bounds slice) if the buffer ends mid-record
+    /// -- a cache file can be half-written by a crash, stale-format, or hand-edited, and a
+    /// corrupt entry on disk must fail soft into a cache miss, not bring down the whole prover
+    /// or compiler invocation.
+    fn next_field(&mut self) -> anyhow::Result<Option<(u8, &'a [u8])>> {
+        if self.pos == self.buf.len() {
+            return Ok(None);
+        }
+        if self.pos + 5 > self.buf.len() {
+            return Err(anyhow::anyhow!(
+                "corrupt cache entry: truncated field header at offset {}",
+                self.pos
+            ));
+        }
+        let tag = self.buf[self.pos];
+        let len =
+            u32::from_le_bytes(self.buf[self.pos + 1..self.pos + 5].try_into().unwrap()) as usize;
+        let start = self.pos + 5;
+        let end = start
+            .checked_add(len)
+            .ok_or_else(|| anyhow::anyhow!("corrupt cache entry: field length overflow"))?;
+        if end > self.buf.len() {
+            return Err(anyhow::anyhow!(
+                "corrupt cache entry: field payload at offset {} runs past end of buffer",
+                self.pos
+            ));
+        }
+        let payload = &self.buf[start..end];
+        self.pos = end;
+        Ok(Some((tag, payload)))
+    }
+}
+
+/// Encodes the stable, cacheable fields of `data` into a tagged binary container.
+pub fn encode(data: &FunctionData) -> anyhow::Result<Vec<u8>> {
+    let mut w = Writer::new();
+    w.field(TAG_CODE, &bcs::to_bytes(&data.code)?);
+    w.field(TAG_LOCAL_TYPES, &bcs::to_bytes(&data.local_types)?);
+    w.field(TAG_RETURN_TYPES, &bcs::to_bytes(&data.return_types)?);
+    w.field(TAG_PARAM_PROXY_MAP, &bcs::to_bytes(&data.param_proxy_map)?);
+    w.field(
+        TAG_REF_PARAM_PROXY_MAP,
+        &bcs::to_bytes(&data.ref_param_proxy_map)?,
+    );
+    w.field(
+        TAG_REF_PARAM_RETURN_MAP,
+        &bcs::to_bytes(&data.ref_param_return_map)?,
+    );
+    w.field(
+        TAG_ACQUIRES_GLOBAL_RESOURCES,
+        &bcs::to_bytes(&data.acquires_global_resources)?,
+    );
+    w.field(TAG_LOCATIONS, &bcs::to_bytes(&data.locations)?);
+    w.field(
+        TAG_SPEC_BLOCKS_ON_IMPL,
+        &bcs::to_bytes(&data.spec_blocks_on_impl)?,
+    );
+    Ok(w.into_bytes())
+}
+
+/// Decodes a tagged container produced by `encode` back into a `FunctionData`, deriving
+/// `name_to_index` and `modify_targets` from `func_env` the same way `FunctionData::new` does,
+/// and leaving `annotations` empty for the caller's analyses to re-populate.
+pub fn decode(bytes: &[u8], func_env: &FunctionEnv<'_>) -> anyhow::Result<FunctionData> {
+    let mut code: Option<Vec<Bytecode>> = None;
+    let mut local_types: Option<Vec<Type>> = None;
+    let mut return_types: Option<Vec<Type>> = None;
+    let mut param_proxy_map: Option<BTreeMap<usize, usize>> = None;
+    let mut ref_param_proxy_map: Option<BTreeMap<usize, usize>> = None;
+    let mut ref_param_return_map: Option<BTreeMap<usize, usize>> = None;
+    let mut acquires_global_resources: Option<Vec<StructId>> = None;
+    let mut locations: Option<BTreeMap<AttrId, Loc>> = None;
+    let mut spec_blocks_on_impl: Option<BTreeMap<SpecBlockId, CodeOffset>> = None;
+
+    let mut reader = Reader::new(bytes);
+    while let Some((tag, payload)) = reader.next_field()? {
+        match tag {
+            TAG_CODE => code = Some(bcs::from_bytes(payload)?),
+            TAG_LOCAL_TYPES => local_types = Some(bcs::from_bytes(payload)?),
+            TAG_RETURN_TYPES => return_types = Some(bcs::from_bytes(payload)?),
+            TAG_PARAM_PROXY_MAP => param_proxy_map = Some(bcs::from_bytes(payload)?),
+            TAG_REF_PARAM_PROXY_MAP => ref_param_proxy_map = Some(bcs::from_bytes(payload)?),
+            TAG_REF_PARAM_RETURN_MAP => ref_param_return_map = Some(bcs::from_bytes(payload)?),
+            TAG_ACQUIRES_GLOBAL_RESOURCES => {
+                acquires_global_resources = Some(bcs::from_bytes(payload)?)
+            }
+            TAG_LOCATIONS => locations = Some(bcs::from_bytes(payload)?),
+            TAG_SPEC_BLOCKS_ON_IMPL => spec_blocks_on_impl = Some(bcs::from_bytes(payload)?),
+            // An unknown tag, presumably written by a different version of this cache; skip it.
+            _ => {}
+        }
+    }
+
+    let name_to_index = (0..func_env.get_local_count())
+        .map(|idx| (func_env.get_local_name(idx), idx))
+        .collect();
+    let modify_targets = func_env.get_modify_targets();
+
+    Ok(FunctionData {
+        code: code.ok_or_else(|| anyhow::anyhow!("cache entry missing code"))?,
+        local_types: local_types.ok_or_else(|| anyhow::anyhow!("cache entry missing local_types"))?,
+        return_types: return_types
+            .ok_or_else(|| anyhow::anyhow!("cache entry missing return_types"))?,
+        param_proxy_map: param_proxy_map.unwrap_or_default(),
+        ref_param_proxy_map: ref_param_proxy_map.unwrap_or_default(),
+        ref_param_return_map: ref_param_return_map.unwrap_or_default(),
+        acquires_global_resources: acquires_global_resources.unwrap_or_default(),
+        locations: locations.unwrap_or_default(),
+        annotations: Default::default(),
+        spec_blocks_on_impl: spec_blocks_on_impl.unwrap_or_default(),
+        name_to_index,
+        modify_targets,
+    })
+}
+
+/// Encodes a function id as a tagged-container payload, independent of whatever `Serialize` impl
+/// (if any) `QualifiedId` happens to have -- `ModuleId`/`FunId` are plain index newtypes, so their
+/// raw indices round-trip through `bcs` directly.
+fn encode_fun_id(fun: &QualifiedId<FunId>) -> anyhow::Result<Vec<u8>> {
+    bcs::to_bytes(&(fun.module_id.as_usize(), fun.id.as_usize()))
+}
+
+fn decode_fun_id(bytes: &[u8]) -> anyhow::Result<QualifiedId<FunId>> {
+    let (module_idx, fun_idx): (usize, usize) = bcs::from_bytes(bytes)?;
+    Ok(ModuleId::new(module_idx).qualified(FunId::new(fun_idx)))
+}
+
+/// Encodes every function tracked by `holder` into a single tagged container, one nested
+/// `TAG_HOLDER_ENTRY` record per function.
+pub fn encode_holder(holder: &FunctionTargetsHolder) -> anyhow::Result<Vec<u8>> {
+    let mut w = Writer::new();
+    for fun in holder.get_funs() {
+        if let Some(data) = holder.get_data(&fun) {
+            let mut entry = Writer::new();
+            entry.field(TAG_ENTRY_FUN_ID, &encode_fun_id(&fun)?);
+            entry.field(TAG_ENTRY_FUNCTION_DATA, &encode(data)?);
+            w.field(TAG_HOLDER_ENTRY, &entry.into_bytes());
+        }
+    }
+    Ok(w.into_bytes())
+}
+
+/// Decodes a container produced by `encode_holder` back into a map from function id to
+/// `FunctionData`, resolving each function's `FunctionEnv` from `env` the same way `decode`
+/// needs one for a single function.
+pub fn decode_holder(
+    bytes: &[u8],
+    env: &GlobalEnv,
+) -> anyhow::Result<BTreeMap<QualifiedId<FunId>, FunctionData>> {
+    let mut result = BTreeMap::new();
+    let mut reader = Reader::new(bytes);
+    while let Some((tag, payload)) = reader.next_field()? {
+        if tag != TAG_HOLDER_ENTRY {
+            continue;
+        }
+        let mut fun_id = None;
+        let mut data_bytes = None;
+        let mut entry_reader = Reader::new(payload);
+        while let Some((entry_tag, entry_payload)) = entry_reader.next_field()? {
+            match entry_tag {
+                TAG_ENTRY_FUN_ID => fun_id = Some(decode_fun_id(entry_payload)?),
+                TAG_ENTRY_FUNCTION_DATA => data_bytes = Some(entry_payload),
+                _ => {}
+            }
+        }
+        let fun_id = fun_id.ok_or_else(|| anyhow::anyhow!("cache entry missing function id"))?;
+        let data_bytes =
+            data_bytes.ok_or_else(|| anyhow::anyhow!("cache entry missing function data"))?;
+        // A stale cache dir can outlive the source it was written for (modules renumbered or
+        // removed since); the get_module()/into_function() lookups below panic on an
+        // out-of-range id, so this must be checked up front rather than trusted, or a corrupt
+        // on-disk entry would crash the whole invocation instead of failing soft into a miss.
+        if fun_id.module_id.as_usize() >= env.get_module_count() {
+            return Err(anyhow::anyhow!(
+                "cache entry refers to module {} which no longer exists",
+                fun_id.module_id.as_usize()
+            ));
+        }
+        let module_env = env.get_module(fun_id.module_id);
+        if fun_id.id.as_usize() >= module_env.get_function_count() {
+            return Err(anyhow::anyhow!(
+                "cache entry refers to function {} which no longer exists in its module",
+                fun_id.id.as_usize()
+            ));
+        }
+        let func_env = module_env.into_function(fun_id.id);
+        result.insert(fun_id, decode(data_bytes, &func_env)?);
+    }
+    Ok(result)
+}
+
+/// A directory of serialized `FunctionData`/`FunctionTargetsHolder` entries, named by
+/// `CacheKey`. A missing or corrupt entry is a cache miss (`None`), not an error -- the caller's
+/// fallback is always to recompute the target from scratch.
+pub struct FunctionDataDiskCache {
+    root: PathBuf,
+}
+
+impl FunctionDataDiskCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FunctionDataDiskCache { root: root.into() }
+    }
+
+    fn path_for(&self, key: CacheKey) -> PathBuf {
+        self.root.join(format!("{}.fun", key.to_hex()))
+    }
+
+    fn holder_path_for(&self, key: CacheKey) -> PathBuf {
+        self.root.join(format!("{}.holder", key.to_hex()))
+    }
+
+    /// Persists `data` under `key`, creating the cache directory if it doesn't exist yet.
+    pub fn store(&self, key: CacheKey, data: &FunctionData) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.root)?;
+        std::fs::write(self.path_for(key), encode(data)?)?;
+        Ok(())
+    }
+
+    /// Loads the `FunctionData` cached under `key`, or `None` if there is no entry, or the entry
+    /// on disk can't be decoded.
+    pub fn load(&self, key: CacheKey, func_env: &FunctionEnv<'_>) -> Option<FunctionData> {
+        let bytes = std::fs::read(self.path_for(key)).ok()?;
+        decode(&bytes, func_env).ok()
+    }
+
+    /// Persists every function tracked by `holder` under `key`, creating the cache directory if
+    /// it doesn't exist yet.
+    pub fn store_holder(
+        &self,
+        key: CacheKey,
+        holder: &FunctionTargetsHolder,
+    ) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.root)?;
+        std::fs::write(self.holder_path_for(key), encode_holder(holder)?)?;
+        Ok(())
+    }
+
+    /// Loads the functions cached under `key`, or `None` if there is no entry, or the entry on
+    /// disk can't be decoded.
+    pub fn load_holder(
+        &self,
+        key: CacheKey,
+        env: &GlobalEnv,
+    ) -> Option<BTreeMap<QualifiedId<FunId>, FunctionData>> {
+        let bytes = std::fs::read(self.holder_path_for(key)).ok()?;
+        decode_holder(&bytes, env).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_round_trips_fields_written_by_writer() {
+        let mut w = Writer::new();
+        w.field(TAG_CODE, b"abc");
+        w.field(TAG_LOCAL_TYPES, b"");
+        let bytes = w.into_bytes();
+
+        let mut r = Reader::new(&bytes);
+        assert_eq!(r.next_field().unwrap(), Some((TAG_CODE, &b"abc"[..])));
+        assert_eq!(r.next_field().unwrap(), Some((TAG_LOCAL_TYPES, &b""[..])));
+        assert_eq!(r.next_field().unwrap(), None);
+    }
+
+    #[test]
+    fn reader_rejects_truncated_header_instead_of_panicking() {
+        // A single byte can't even hold a tag + length header.
+        let bytes = [TAG_CODE];
+        let mut r = Reader::new(&bytes);
+        assert!(r.next_field().is_err());
+    }
+
+    #[test]
+    fn reader_rejects_payload_past_end_of_buffer_instead_of_panicking() {
+        let mut bytes = vec![TAG_CODE];
+        bytes.extend_from_slice(&100u32.to_le_bytes()); // claims a 100-byte payload
+        bytes.extend_from_slice(b"short"); // but only 5 bytes follow
+        let mut r = Reader::new(&bytes);
+        assert!(r.next_field().is_err());
+    }
+
+    #[test]
+    fn fun_id_round_trips_through_its_raw_indices() {
+        let fun = ModuleId::new(3).qualified(FunId::new(5));
+        let bytes = encode_fun_id(&fun).unwrap();
+        assert_eq!(decode_fun_id(&bytes).unwrap(), fun);
+    }
+
+    #[test]
+    fn holder_entries_nest_a_fun_id_and_function_data_container_inside_one_tagged_record() {
+        // Mirrors the nesting `encode_holder` writes, without needing a real
+        // `FunctionTargetsHolder` to build it.
+        let fun = ModuleId::new(1).qualified(FunId::new(2));
+        let data = empty_function_data();
+
+        let mut entry = Writer::new();
+        entry.field(TAG_ENTRY_FUN_ID, &encode_fun_id(&fun).unwrap());
+        entry.field(TAG_ENTRY_FUNCTION_DATA, &encode(&data).unwrap());
+        let mut outer = Writer::new();
+        outer.field(TAG_HOLDER_ENTRY, &entry.into_bytes());
+        let bytes = outer.into_bytes();
+
+        let mut reader = Reader::new(&bytes);
+        let (tag, payload) = reader.next_field().unwrap().unwrap();
+        assert_eq!(tag, TAG_HOLDER_ENTRY);
+        assert_eq!(reader.next_field().unwrap(), None);
+
+        let mut entry_reader = Reader::new(payload);
+        let (entry_tag, fun_id_bytes) = entry_reader.next_field().unwrap().unwrap();
+        assert_eq!(entry_tag, TAG_ENTRY_FUN_ID);
+        assert_eq!(decode_fun_id(fun_id_bytes).unwrap(), fun);
+        let (entry_tag, _) = entry_reader.next_field().unwrap().unwrap();
+        assert_eq!(entry_tag, TAG_ENTRY_FUNCTION_DATA);
+    }
+
+    /// A minimal `FunctionData` with no locals, code, or maps -- enough to exercise `encode`
+    /// without needing a real `FunctionEnv`.
+    fn empty_function_data() -> FunctionData {
+        FunctionData {
+            code: vec![],
+            local_types: vec![],
+            return_types: vec![],
+            param_proxy_map: BTreeMap::new(),
+            ref_param_proxy_map: BTreeMap::new(),
+            ref_param_return_map: BTreeMap::new(),
+            acquires_global_resources: vec![],
+            locations: BTreeMap::new(),
+            annotations: Default::default(),
+            spec_blocks_on_impl: BTreeMap::new(),
+            name_to_index: BTreeMap::new(),
+            modify_targets: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn disk_cache_store_writes_the_same_bytes_encode_would_produce() {
+        let dir = std::env::temp_dir().join(format!(
+            "move-prover-function-data-cache-test-{}",
+            std::process::id()
+        ));
+        let cache = FunctionDataDiskCache::new(&dir);
+        let key = CacheKey::new(b"source", b"pipeline");
+        let data = empty_function_data();
+
+        cache.store(key, &data).unwrap();
+        let on_disk = std::fs::read(cache.path_for(key)).unwrap();
+        assert_eq!(on_disk, encode(&data).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn disk_cache_paths_for_single_function_and_holder_entries_never_collide() {
+        let cache = FunctionDataDiskCache::new("/tmp/move-prover-function-data-cache");
+        let key = CacheKey::new(b"source", b"pipeline");
+        assert_ne!(cache.path_for(key), cache.holder_path_for(key));
+    }
+}
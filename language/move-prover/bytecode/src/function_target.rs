@@ -3,7 +3,10 @@
 
 use crate::{
     annotations::Annotations,
-    borrow_analysis, livevar_analysis, reaching_def_analysis,
+    borrow_analysis,
+    bytecode_rewriter::{self, BytecodeRewriter},
+    control_flow_graph::ControlFlowGraph,
+    livevar_analysis, reaching_def_analysis,
     stackless_bytecode::{AttrId, Bytecode, Operation, SpecBlockId, TempIndex},
 };
 use itertools::Itertools;
@@ -29,6 +32,9 @@ pub struct FunctionTarget<'env> {
 
     // Used for debugging and testing, containing any attached annotation formatters.
     annotation_formatters: RefCell<Vec<Box<AnnotationFormatter>>>,
+    // Formatters which additionally contribute a typed payload, for structured consumers; see
+    // `StructuredAnnotationFormatter`.
+    structured_annotation_formatters: RefCell<Vec<Box<StructuredAnnotationFormatter>>>,
 }
 
 impl<'env> Clone for FunctionTarget<'env> {
@@ -39,6 +45,7 @@ impl<'env> Clone for FunctionTarget<'env> {
             func_env: self.func_env,
             data: self.data,
             annotation_formatters: RefCell::new(vec![]),
+            structured_annotation_formatters: RefCell::new(vec![]),
         }
     }
 }
@@ -89,6 +96,7 @@ impl<'env> FunctionTarget<'env> {
             func_env,
             data,
             annotation_formatters: RefCell::new(vec![]),
+            structured_annotation_formatters: RefCell::new(vec![]),
         }
     }
 
@@ -253,6 +261,13 @@ impl<'env> FunctionTarget<'env> {
         &self.data.code
     }
 
+    /// Builds the control flow graph of this function. This partitions `get_bytecode` into
+    /// basic blocks and is recomputed on every call rather than cached on `FunctionData`, since
+    /// it must stay in sync with whatever the current processor has rewritten the code to.
+    pub fn get_control_flow_graph(&self) -> ControlFlowGraph {
+        ControlFlowGraph::new(self.get_bytecode())
+    }
+
     /// Gets annotations.
     pub fn get_annotations(&self) -> &Annotations {
         &self.data.annotations
@@ -397,11 +412,22 @@ impl FunctionData {
         callees
     }
 
-    /// Apply a variable renaming to this data, adjusting internal data structures.
+    /// Apply a variable renaming to this data, adjusting internal data structures. The code is
+    /// renamed through a `BytecodeRewriter` whose `rewrite_temp` is `f`, and `param_proxy_map`
+    /// and `ref_param_proxy_map` are remapped with the same `f`; `ref_param_return_map` is left
+    /// unchanged.
     pub fn rename_vars<F>(&mut self, f: &F)
     where
         F: Fn(TempIndex) -> TempIndex,
     {
+        struct RenameRewriter<'a, F>(&'a F);
+        impl<'a, F: Fn(TempIndex) -> TempIndex> BytecodeRewriter for RenameRewriter<'a, F> {
+            fn rewrite_temp(&mut self, temp: TempIndex) -> TempIndex {
+                (self.0)(temp)
+            }
+        }
+        let mut rewriter = RenameRewriter(f);
+        self.code = bytecode_rewriter::rewrite_code(&self.code, &mut rewriter);
         self.param_proxy_map = std::mem::take(&mut self.param_proxy_map)
             .into_iter()
             .map(|(x, y)| (f(x), f(y)))
@@ -442,6 +468,26 @@ impl Clone for FunctionData {
 /// the given code offset. It should return None if there is no relevant annotation.
 pub type AnnotationFormatter = dyn Fn(&FunctionTarget<'_>, CodeOffset) -> Option<String>;
 
+/// A typed value an annotation can contribute in addition to (or instead of) its human-readable
+/// rendering, so a structured consumer (e.g. `function_target_export`) can distinguish, say, a
+/// live-variable set from a borrow annotation without re-parsing the `Display` text.
+#[derive(Debug, Clone, serde::Serialize)]
+pub enum AnnotationValue {
+    /// A set of temporaries, e.g. a live-variable or modified-locals annotation.
+    Temps(BTreeSet<TempIndex>),
+    /// A set of structural relationships, rendered as label strings, e.g. borrow edges.
+    Labels(BTreeSet<String>),
+    /// Anything without a more specific representation; falls back to the same text used for
+    /// `Display`.
+    Text(String),
+}
+
+/// Like `AnnotationFormatter`, but contributes a stable key identifying the kind of annotation
+/// alongside a typed `AnnotationValue`, for consumers that want to consume annotations
+/// programmatically rather than by screen-scraping `Display` output.
+pub type StructuredAnnotationFormatter =
+    dyn Fn(&FunctionTarget<'_>, CodeOffset) -> Option<(&'static str, AnnotationValue)>;
+
 impl<'env> FunctionTarget<'env> {
     /// Register a formatter. Each function target processor which introduces new annotations
     /// should register a formatter in order to get is value printed when a function target
@@ -450,6 +496,21 @@ impl<'env> FunctionTarget<'env> {
         self.annotation_formatters.borrow_mut().push(formatter);
     }
 
+    /// Register a structured formatter, analogous to `register_annotation_formatter` but
+    /// contributing a typed `AnnotationValue` for structured export (see
+    /// `function_target_export::to_export`) rather than only a display string. Use this to
+    /// layer on an analysis-specific formatter beyond the built-ins `get_structured_annotations_at`
+    /// always includes, or to override one of those built-ins by registering the same key with a
+    /// richer `Temps`/`Labels` payload.
+    pub fn register_structured_annotation_formatter(
+        &self,
+        formatter: Box<StructuredAnnotationFormatter>,
+    ) {
+        self.structured_annotation_formatters
+            .borrow_mut()
+            .push(formatter);
+    }
+
     /// Tests use this function to register all relevant annotation formatters. Extend this with
     /// new formatters relevant for tests.
     pub fn register_annotation_formatters_for_test(&self) {
@@ -459,6 +520,48 @@ impl<'env> FunctionTarget<'env> {
             reaching_def_analysis::format_reaching_def_annotation,
         ));
     }
+
+    /// Collects the typed payload of every structured annotation at `offset`, keyed by its
+    /// stable kind key. Always includes `livevar`/`borrow`/`reaching_def`, wrapping
+    /// `livevar_analysis`/`borrow_analysis`/`reaching_def_analysis`'s existing `String` rendering
+    /// as `AnnotationValue::Text` -- unlike `register_annotation_formatter`, these three need no
+    /// registration step, so `to_export()` carries this data on every real invocation, not only
+    /// when a test harness has opted in. Anything registered via
+    /// `register_structured_annotation_formatter` is layered on top, and can override a built-in
+    /// key by registering the same one with a richer payload.
+    pub fn get_structured_annotations_at(
+        &self,
+        offset: CodeOffset,
+    ) -> BTreeMap<&'static str, AnnotationValue> {
+        let mut result: BTreeMap<&'static str, AnnotationValue> = [
+            livevar_analysis::format_livevar_annotation(self, offset)
+                .map(|s| ("livevar", AnnotationValue::Text(s))),
+            borrow_analysis::format_borrow_annotation(self, offset)
+                .map(|s| ("borrow", AnnotationValue::Text(s))),
+            reaching_def_analysis::format_reaching_def_annotation(self, offset)
+                .map(|s| ("reaching_def", AnnotationValue::Text(s))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        layer_structured_annotations(
+            &mut result,
+            self.structured_annotation_formatters
+                .borrow()
+                .iter()
+                .filter_map(|f| f(self, offset)),
+        );
+        result
+    }
+}
+
+/// Layers `extra` on top of `built_ins` in place, so a key an explicitly registered formatter
+/// also produces overrides the built-in entry of the same key rather than the two coexisting.
+fn layer_structured_annotations(
+    built_ins: &mut BTreeMap<&'static str, AnnotationValue>,
+    extra: impl Iterator<Item = (&'static str, AnnotationValue)>,
+) {
+    built_ins.extend(extra);
 }
 
 impl<'env> fmt::Display for FunctionTarget<'env> {
@@ -550,3 +653,33 @@ impl<'env> fmt::Display for FunctionTarget<'env> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `get_structured_annotations_at` itself needs a real `FunctionTarget` (and so a full
+    // `FunctionEnv`/`GlobalEnv` from `move_model`), which this tree has no harness for; this
+    // exercises the override semantics it relies on -- an explicitly registered formatter's key
+    // wins over a built-in of the same key -- in isolation instead.
+    #[test]
+    fn layering_overrides_a_built_in_key_with_the_same_key_from_an_explicit_formatter() {
+        let mut built_ins = BTreeMap::new();
+        built_ins.insert("livevar", AnnotationValue::Text("built-in".to_string()));
+        built_ins.insert("borrow", AnnotationValue::Text("unrelated".to_string()));
+
+        layer_structured_annotations(
+            &mut built_ins,
+            vec![("livevar", AnnotationValue::Temps(BTreeSet::from([1, 2])))].into_iter(),
+        );
+
+        assert!(matches!(
+            built_ins.get("livevar"),
+            Some(AnnotationValue::Temps(temps)) if *temps == BTreeSet::from([1, 2])
+        ));
+        assert!(matches!(
+            built_ins.get("borrow"),
+            Some(AnnotationValue::Text(s)) if s == "unrelated"
+        ));
+    }
+}
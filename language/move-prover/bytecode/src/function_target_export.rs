@@ -0,0 +1,118 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A structured, serde-serializable export of a `FunctionTarget`: its signature, locals,
+//! numbered bytecode with per-offset annotations gathered from the registered structured
+//! formatters, and its verification conditions. The `Display` impl on `FunctionTarget` renders
+//! the same information as human-readable text; this gives external tooling (IDEs, dashboards,
+//! diff tools) a stable object to consume instead of screen-scraping that text.
+
+use crate::function_target::{AnnotationValue, FunctionTarget};
+use move_model::{model::TypeParameter, ty::TypeDisplayContext};
+use std::collections::BTreeMap;
+use vm::file_format::CodeOffset;
+
+/// A local variable or parameter in a `FunctionExport`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LocalExport {
+    pub name: String,
+    pub ty: String,
+}
+
+/// A single bytecode instruction in a `FunctionExport`, with its offset, rendered text, and any
+/// structured annotations contributed at that offset.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct InstructionExport {
+    pub offset: CodeOffset,
+    pub text: String,
+    pub annotations: BTreeMap<&'static str, AnnotationValue>,
+}
+
+/// A verification condition associated with a `Prop` instruction in a `FunctionExport`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConditionExport {
+    pub tag: String,
+    pub info: String,
+}
+
+/// The structured export of a `FunctionTarget`, produced by `FunctionTarget::to_export`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FunctionExport {
+    pub module: String,
+    pub name: String,
+    pub is_public: bool,
+    pub type_parameters: Vec<String>,
+    pub parameters: Vec<LocalExport>,
+    pub locals: Vec<LocalExport>,
+    pub return_types: Vec<String>,
+    pub instructions: Vec<InstructionExport>,
+    pub verification_conditions: Vec<ConditionExport>,
+}
+
+impl<'env> FunctionTarget<'env> {
+    /// Produces a structured export of this function target, mirroring the information shown by
+    /// `Display` but as a serde-serializable object rather than text.
+    pub fn to_export(&self) -> FunctionExport {
+        let tctx = TypeDisplayContext::WithEnv {
+            env: self.global_env(),
+            type_param_names: None,
+        };
+
+        let type_parameters = self
+            .get_type_parameters()
+            .iter()
+            .map(|TypeParameter(name, _)| name.display(self.symbol_pool()).to_string())
+            .collect();
+        let local_export = |idx: usize| LocalExport {
+            name: self.get_local_name(idx).display(self.symbol_pool()).to_string(),
+            ty: self.get_local_type(idx).display(&tctx).to_string(),
+        };
+        let parameters = (0..self.get_parameter_count()).map(local_export).collect();
+        let locals = (self.get_parameter_count()..self.get_local_count())
+            .map(local_export)
+            .collect();
+        let return_types = (0..self.get_return_count())
+            .map(|i| self.get_return_type(i).display(&tctx).to_string())
+            .collect();
+
+        let mut instructions = Vec::with_capacity(self.get_bytecode().len());
+        let mut verification_conditions = Vec::new();
+        let mut loc_vc_shown = std::collections::BTreeSet::new();
+        for (offset, code) in self.get_bytecode().iter().enumerate() {
+            let offset = offset as CodeOffset;
+            if let Some(loc) = self.data.locations.get(&code.get_attr_id()) {
+                if matches!(code, crate::stackless_bytecode::Bytecode::Prop(..))
+                    && loc_vc_shown.insert(loc.clone())
+                {
+                    for (tag, info) in self.func_env.module_env.env.get_condition_infos(loc) {
+                        verification_conditions.push(ConditionExport {
+                            tag: tag.to_string(),
+                            info: info.to_string(),
+                        });
+                    }
+                }
+            }
+            instructions.push(InstructionExport {
+                offset,
+                text: code.display(self).to_string(),
+                annotations: self.get_structured_annotations_at(offset),
+            });
+        }
+
+        FunctionExport {
+            module: self
+                .module_env()
+                .get_name()
+                .display(self.symbol_pool())
+                .to_string(),
+            name: self.get_name().display(self.symbol_pool()).to_string(),
+            is_public: self.is_public(),
+            type_parameters,
+            parameters,
+            locals,
+            return_types,
+            instructions,
+            verification_conditions,
+        }
+    }
+}
@@ -0,0 +1,21 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod annotations;
+pub mod borrow_analysis;
+pub mod bytecode_rewriter;
+pub mod call_graph;
+pub mod control_flow_graph;
+pub mod function_data_cache;
+pub mod function_target;
+pub mod function_target_export;
+pub mod function_target_pipeline;
+pub mod livevar_analysis;
+pub mod reaching_def_analysis;
+pub mod stackless_bytecode;
+
+pub use bytecode_rewriter::BytecodeRewriter;
+pub use call_graph::{CallGraph, StronglyConnectedComponents};
+pub use control_flow_graph::{ControlFlowGraph, DominatorTree};
+pub use function_data_cache::{CacheKey, FunctionDataDiskCache};
+pub use function_target_export::FunctionExport;